@@ -8,6 +8,12 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+extern mod extra;
+
+use extra::arc::Arc;
+use extra::future::Future;
+use std::unstable::atomics::{AtomicInt, SeqCst};
+
 //
 // Utilities.
 //
@@ -63,6 +69,63 @@ impl<'self, T> Iterator<&'self T> for ListIterator<'self, T> {
 // preprocess
 //
 
+// The board the solver runs over: a hex board of `width` by `height`
+// cells tiled by `num_pieces` distinct pieces.
+#[deriving(Clone)]
+struct Geometry {
+    width: uint,
+    height: uint,
+    num_pieces: uint
+}
+
+impl Geometry {
+    // Builds a geometry, checking it still fits in this solver's u64
+    // mask word (50 cell bits + 10 piece bits = 60, for the standard
+    // puzzle).  `Mask` stays a `u64` for now, so this is a hard
+    // ceiling, not a soft one: boards whose cells-plus-pieces reaches
+    // 64 are not solvable with this backend at all (is_board_unfeasible
+    // builds an all-ones mask via `1 << geo.bits()`, which would itself
+    // overflow a u64 at exactly 64 bits), and fail loudly here rather
+    // than silently misbehaving later.
+    fn new(width: uint, height: uint, num_pieces: uint) -> Geometry {
+        let geo = Geometry {width: width, height: height, num_pieces: num_pieces};
+        assert!(geo.bits() < 64,
+            "{}x{} board with {} pieces needs {} mask bits; only boards up to 63 \
+             bits fit in this solver's u64 mask word",
+            width, height, num_pieces, geo.bits());
+        geo
+    }
+
+    // Number of cells on the board.
+    fn cells(&self) -> uint {self.width * self.height}
+
+    // Total width of a mask for this board: one bit per cell plus one
+    // bit per piece.
+    fn bits(&self) -> uint {self.cells() + self.num_pieces}
+}
+
+// The standard 5-wide, 10-tall Meteor puzzle's ten pieces.
+fn default_pieces() -> ~[~[(int, int)]] {
+    ~[
+        ~[(0,0),(0,1),(0,2),(0,3),(1,3)],
+        ~[(0,0),(0,2),(0,3),(1,0),(1,1)],
+        ~[(0,0),(0,1),(0,2),(1,2),(2,1)],
+        ~[(0,0),(0,1),(0,2),(1,1),(2,1)],
+        ~[(0,0),(0,2),(1,0),(1,1),(2,1)],
+        ~[(0,0),(0,1),(0,2),(1,1),(1,2)],
+        ~[(0,0),(0,1),(1,1),(1,2),(2,1)],
+        ~[(0,0),(0,1),(0,2),(1,0),(1,2)],
+        ~[(0,0),(0,1),(0,2),(1,2),(1,3)],
+        ~[(0,0),(0,1),(0,2),(0,3),(1,2)]]
+}
+// The standard board has a symmetry under a half-turn rotation, which
+// lets one piece (by convention, piece 3 of `default_pieces`) skip
+// half its transforms; see `make_masks` and `handle_sol`.  Boards
+// built from a different geometry or piece set don't necessarily have
+// that symmetry, so callers must say so explicitly instead of it
+// being assumed.
+fn default_symmetric_piece() -> Option<uint> {Some(3)}
+
 // Takes a pieces p on the form [(y1, x1), (y2, x2), ...] and returns
 // every possible transformations (the 6 rotations with their
 // corresponding mirrored piece), with, as minimum coordinates, (0,
@@ -89,52 +152,102 @@ fn transform(p: ~[(int, int)], all: bool) -> ~[~[(int, int)]] {
     res
 }
 
-// A mask is a piece somewere on the board.  It is represented as a
-// u64: for i in the first 50 bits, m[i] = 1 if the cell at (i/5, i%5)
-// is occuped.  m[50 + id] = 1 if the identifier of the piece is id.
+// A mask is a piece somewere on the board: `geo.cells()` bits of cell
+// occupancy packed together with a `geo.num_pieces`-bit one-hot piece
+// identifier above them, reached through named accessors instead of
+// hand-written shifts.
+#[deriving(Eq, Clone)]
+struct Mask(u64);
+
+impl Mask {
+    // A fresh mask for piece `id`, with no cell occupied yet.
+    fn for_piece(geo: &Geometry, id: uint) -> Mask {
+        assert!(id < geo.num_pieces);
+        Mask(1 << (geo.cells() + id))
+    }
+
+    // The cell-occupancy field.
+    fn cells(&self, geo: &Geometry) -> u64 {
+        let Mask(m) = *self;
+        m & ((1 << geo.cells()) - 1)
+    }
+
+    // The identifier of the piece this mask belongs to.  Exactly one
+    // bit of the piece field is set, so its position is the
+    // identifier.
+    fn piece_id(&self, geo: &Geometry) -> u8 {
+        let Mask(m) = *self;
+        let piece_mask = ((1 << geo.num_pieces) - 1) << geo.cells();
+        let piece = (m & piece_mask) >> geo.cells();
+        assert!(piece != 0, "{:016x} does not have a valid identifier", m);
+        piece.trailing_zeros() as u8
+    }
+
+    // Marks cell `i` as occupied.
+    fn set_cell(&mut self, i: int) {
+        let Mask(m) = *self;
+        *self = Mask(m | 1 << i);
+    }
+
+    // Whether the two masks occupy any cell in common.
+    fn overlaps(&self, geo: &Geometry, other: Mask) -> bool {
+        let Mask(b) = other;
+        self.cells(geo) & (b & ((1 << geo.cells()) - 1)) != 0
+    }
+
+    // Whether piece `id` is the one this mask is marked as using.
+    fn uses_piece(&self, geo: &Geometry, id: uint) -> bool {
+        let Mask(m) = *self;
+        m & (1 << (geo.cells() + id)) != 0
+    }
+}
+impl BitOr<Mask, Mask> for Mask {
+    fn bitor(&self, other: &Mask) -> Mask {
+        let (Mask(a), Mask(b)) = (*self, *other);
+        Mask(a | b)
+    }
+}
+impl BitAnd<Mask, Mask> for Mask {
+    fn bitand(&self, other: &Mask) -> Mask {
+        let (Mask(a), Mask(b)) = (*self, *other);
+        Mask(a & b)
+    }
+}
 
 // Takes a piece with minimum coordinate (0, 0) (as generated by
 // transform).  Returns the corresponding mask if p translated by (dy,
 // dx) is on the board.
-fn mask(dy: int, dx: int, id: uint, p: &[(int, int)]) -> Option<u64> {
-    let mut m = 1 << (50 + id);
+fn mask(geo: &Geometry, dy: int, dx: int, id: uint, p: &[(int, int)]) -> Option<Mask> {
+    let width = geo.width as int;
+    let height = geo.height as int;
+    let mut m = Mask::for_piece(geo, id);
     for &(y, x) in p.iter() {
         let x = x + dx + (y + (dy % 2)) / 2;
-        if x < 0 || x > 4 {return None;}
+        if x < 0 || x >= width {return None;}
         let y = y + dy;
-        if y < 0 || y > 9 {return None;}
-        m |= 1 << (y * 5 + x);
+        if y < 0 || y >= height {return None;}
+        m.set_cell(y * width + x);
     }
     Some(m)
 }
 
 // Makes every possible masks.  masks[id][i] correspond to every
 // possible masks for piece with identifier id with minimum coordinate
-// (i/5, i%5).
-fn make_masks() -> ~[~[~[u64]]] {
-    let pieces = ~[
-        ~[(0,0),(0,1),(0,2),(0,3),(1,3)],
-        ~[(0,0),(0,2),(0,3),(1,0),(1,1)],
-        ~[(0,0),(0,1),(0,2),(1,2),(2,1)],
-        ~[(0,0),(0,1),(0,2),(1,1),(2,1)],
-        ~[(0,0),(0,2),(1,0),(1,1),(2,1)],
-        ~[(0,0),(0,1),(0,2),(1,1),(1,2)],
-        ~[(0,0),(0,1),(1,1),(1,2),(2,1)],
-        ~[(0,0),(0,1),(0,2),(1,0),(1,2)],
-        ~[(0,0),(0,1),(0,2),(1,2),(1,3)],
-        ~[(0,0),(0,1),(0,2),(0,3),(1,2)]];
+// (i / geo.width, i % geo.width).  `symmetric_piece`, if any, is the
+// one piece allowed to skip half its transforms to break a central
+// symmetry of the board (see `default_symmetric_piece`).
+fn make_masks(geo: &Geometry, pieces: ~[~[(int, int)]], symmetric_piece: Option<uint>) -> ~[~[~[Mask]]] {
+    assert!(pieces.len() == geo.num_pieces,
+        "geometry calls for {} pieces but {} were given", geo.num_pieces, pieces.len());
     let mut res = ~[];
     for (id, p) in pieces.move_iter().enumerate() {
-        // To break the central symetry of the problem, every
-        // transformation must be taken except for one piece (piece 3
-        // here).
-        let trans = transform(p, id != 3);
+        let trans = transform(p, symmetric_piece != Some(id));
         let mut cur_piece = ~[];
-        for dy in range(0, 10) {
-            for dx in range(0, 5) {
-                let masks = 
+        for dy in range(0, geo.height as int) {
+            for dx in range(0, geo.width as int) {
+                let masks =
                     trans.iter()
-                    .filter_map(|t| mask(dy, dx, id, *t))
+                    .filter_map(|t| mask(geo, dy, dx, id, *t))
                     .collect();
                 cur_piece.push(masks);
             }
@@ -146,59 +259,51 @@ fn make_masks() -> ~[~[~[u64]]] {
 
 // Check if all coordinates can be covered by an unused piece and that
 // all unused piece can be placed on the board.
-fn is_board_unfeasible(board: u64, masks: &[~[~[u64]]]) -> bool {
+fn is_board_unfeasible(board: Mask, geo: &Geometry, masks: &[~[~[Mask]]]) -> bool {
     let mut coverable = board;
-    for i in range(0, 50).filter(|&i| board & 1 << i == 0) {
+    for i in range(0, geo.cells()).filter(|&i| board.cells(geo) & 1 << i == 0) {
         for (cur_id, pos_masks) in masks.iter().enumerate() {
-            if board & 1 << (50 + cur_id) != 0 {continue;}
+            if board.uses_piece(geo, cur_id) {continue;}
             for &cur_m in pos_masks[i].iter() {
-                if cur_m & board == 0 {coverable |= cur_m;}
+                if !cur_m.overlaps(geo, board) {coverable = coverable | cur_m;}
             }
         }
-        if coverable & (1 << i) == 0 {return true;}
+        if coverable.cells(geo) & (1 << i) == 0 {return true;}
     }
     // check if every coordinates can be covered and every piece can
     // be used.
-    coverable != (1 << 60) - 1
+    coverable != Mask((1 << geo.bits()) - 1)
 }
 
 // Filter the masks that we can prove to result to unfeasible board.
-fn filter_masks(masks: &[~[~[u64]]]) -> ~[~[~[u64]]] {
+fn filter_masks(geo: &Geometry, masks: &[~[~[Mask]]]) -> ~[~[~[Mask]]] {
     masks.iter().map(
         |p| p.iter().map(
             |p| p.iter()
                 .map(|&m| m)
-                .filter(|&m| !is_board_unfeasible(m, masks))
+                .filter(|&m| !is_board_unfeasible(m, geo, masks))
                 .collect())
             .collect())
         .collect()
 }
 
-// Gets the identifier of a mask.
-fn get_id(m: u64) -> u8 {
-    for id in range(0, 10) {
-        if m & (1 << (id + 50)) != 0 {return id as u8;}
-    }
-    fail!("{:016x} does not have a valid identifier", m);
-}
-
-// Converts a list of mask to a ~str.
-fn to_utf8(raw_sol: &List<u64>) -> ~str {
-    let mut sol: ~[u8] = std::vec::from_elem(50, '.' as u8);
+// Converts a slice of masks to a ~str.
+fn to_utf8(raw_sol: &[Mask], geo: &Geometry) -> ~str {
+    let mut sol: ~[u8] = std::vec::from_elem(geo.cells(), '.' as u8);
     for &m in raw_sol.iter() {
-        let id = get_id(m);
-        for i in range(0, 50) {
-            if m & 1 << i != 0 {sol[i] = '0' as u8 + id;}
+        let id = m.piece_id(geo);
+        for i in range(0, geo.cells()) {
+            if m.cells(geo) & 1 << i != 0 {sol[i] = '0' as u8 + id;}
         }
     }
     std::str::from_utf8_owned(sol)
 }
 
 // Prints a solution in ~str form.
-fn print_sol(sol: &str) {
+fn print_sol(sol: &str, geo: &Geometry) {
     for (i, c) in sol.iter().enumerate() {
-        if (i) % 5 == 0 {println("");}
-        if (i + 5) % 10 == 0 {print(" ");}
+        if i % geo.width == 0 {println("");}
+        if (i + geo.width) % (geo.width * 2) == 0 {print(" ");}
         print!("{} ", c);
     }
     println("");
@@ -208,58 +313,111 @@ fn print_sol(sol: &str) {
 struct Data {
     // If more than stop_after is found, stop the search.
     stop_after: int,
-    // Number of solution found.
+    // Number of solution found by this accumulator.
     nb: int,
     // Lexicographically minimal solution found.
     min: ~str,
     // Lexicographically maximal solution found.
-    max: ~str
+    max: ~str,
+    // Whether every solution corresponds to two boards (itself and
+    // its half-turn rotation), because of the symmetry break in
+    // `make_masks`.  Only valid for a geometry/piece set that
+    // actually has that symmetry.
+    count_rotation: bool,
+    // When the top-level search is fanned out across worker
+    // threads, every thread's `Data` shares this counter so
+    // `handle_sol` can stop the whole search, not just its own
+    // thread, once the global solution count crosses `stop_after`.
+    shared: Option<Arc<AtomicInt>>
+}
+
+impl Data {
+    // Combines a worker thread's partial result into `self`: `nb` is
+    // summed and `min`/`max` take the lexicographic extreme across
+    // both, the same computation `handle_sol` already does solution
+    // by solution.
+    fn merge(&mut self, other: Data) {
+        if other.nb == 0 {return;}
+        if self.nb == 0 {
+            self.min = other.min;
+            self.max = other.max;
+        } else {
+            if other.min < self.min {self.min = other.min;}
+            if other.max > self.max {self.max = other.max;}
+        }
+        self.nb += other.nb;
+    }
 }
 
 // Records a new found solution.  Returns false if the search must be
 // stopped.
-fn handle_sol(raw_sol: &List<u64>, data: &mut Data) -> bool {
-    // because we break the symetry, 2 solutions correspond to a call
-    // to this method: the normal solution, and the same solution in
-    // reverse order, i.e. the board rotated by half a turn.
-    data.nb += 2;
-    let sol1 = to_utf8(raw_sol);
-    let sol2: ~str = sol1.iter().invert().collect();
+fn handle_sol(raw_sol: &List<Mask>, geo: &Geometry, data: &mut Data) -> bool {
+    let placed: ~[Mask] = raw_sol.iter().map(|&m| m).collect();
+    let sol1 = to_utf8(placed, geo);
+
+    if data.count_rotation {
+        // because we break the symetry, 2 solutions correspond to a
+        // call to this method: the normal solution, and the same
+        // solution in reverse order, i.e. the board rotated by half a
+        // turn.
+        data.nb += 2;
+        let sol2: ~str = sol1.iter().invert().collect();
 
-    if data.nb == 2 {
-        data.min = sol1.clone();
-        data.max = sol1.clone();
+        if data.nb == 2 {
+            data.min = sol1.clone();
+            data.max = sol1.clone();
+        }
+
+        if sol1 < data.min {data.min = sol1.clone();}
+        if sol2 < data.min {data.min = sol2.clone();}
+        if sol1 > data.max {data.max = sol1;}
+        if sol2 > data.max {data.max = sol2;}
+    } else {
+        data.nb += 1;
+        if data.nb == 1 {
+            data.min = sol1.clone();
+            data.max = sol1.clone();
+        }
+        if sol1 < data.min {data.min = sol1.clone();}
+        if sol1 > data.max {data.max = sol1;}
     }
 
-    if sol1 < data.min {data.min = sol1.clone();}
-    if sol2 < data.min {data.min = sol2.clone();}
-    if sol1 > data.max {data.max = sol1;}
-    if sol2 > data.max {data.max = sol2;}
-    data.nb < data.stop_after
+    match data.shared {
+        // Racing a single shared counter up means every worker
+        // thread sees (and stops on) the same global total, instead
+        // of each thread only comparing against its own `nb`.
+        Some(ref counter) => {
+            let step = if data.count_rotation {2} else {1};
+            counter.get().fetch_add(step, SeqCst) + step < data.stop_after
+        }
+        None => data.nb < data.stop_after,
+    }
 }
 
 // Search for every solutions.  Returns false if the search was
 // stopped before the end.
 fn search(
-    masks: &[~[~[u64]]],
-    board: u64,
+    masks: &[~[~[Mask]]],
+    geo: &Geometry,
+    board: Mask,
     mut i: int,
-    cur: List<u64>,
+    cur: List<Mask>,
     data: &mut Data)
     -> bool
 {
+    let n = geo.cells() as int;
     // Search for the lesser empty coordinate.
-    while board & (1 << i)  != 0 && i < 50 {i += 1;}
+    while board.cells(geo) & (1 << i) != 0 && i < n {i += 1;}
     // the board is full: a solution is found.
-    if i >= 50 {return handle_sol(&cur, data);}
+    if i >= n {return handle_sol(&cur, geo, data);}
 
     // for every unused piece
-    for id in range(0, 10).filter(|id| board & (1 << (id + 50)) == 0) {
+    for id in range(0, geo.num_pieces).filter(|&id| !board.uses_piece(geo, id)) {
         // for each mask that fits on the board
-        for &m in masks[id][i].iter().filter(|&m| board & *m == 0) {
+        for &m in masks[id][i as uint].iter().filter(|&m| !m.overlaps(geo, board)) {
             // This check is too costy.
-            //if is_board_unfeasible(board | m, masks) {continue;}
-            if !search(masks, board | m, i + 1, Cons(m, &cur), data) {
+            //if is_board_unfeasible(board | m, geo, masks) {continue;}
+            if !search(masks, geo, board | m, i + 1, Cons(m, &cur), data) {
                 return false;
             }
         }
@@ -267,19 +425,459 @@ fn search(
     return true;
 }
 
+//
+// Iterator API.
+//
+// `solutions` exposes the same DFS as `search` as a lazy `Iterator`
+// instead of an accumulator, yielding each board as it is found.
+// Since the DFS is naturally recursive, the traversal is reimplemented
+// here as an explicit stack of frames so `next()` can suspend between
+// solutions and resume where it left off.
+
+// A single discovered board: the rendered grid (what `to_utf8`
+// builds) and the masks placed to get there, in placement order.
+// `masks` is `None` for a half-turn-rotated twin (see `next`), since
+// those masks describe the unrotated placement and so don't match
+// `board` cell-for-cell.
+struct Solution {
+    board: ~str,
+    masks: Option<~[Mask]>
+}
+
+// One level of the explicit DFS stack: the board as it stood on
+// entry to cell `i`, and every mask that could still go there, with
+// `cursor` marking how far through them this level has gotten.
+struct Frame {
+    board: Mask,
+    i: int,
+    candidates: ~[Mask],
+    cursor: uint
+}
+
+struct Solutions<'self> {
+    priv masks: &'self [~[~[Mask]]],
+    priv geo: &'self Geometry,
+    // Whether every solution has a half-turn-rotated twin, as in
+    // `Data::count_rotation`.
+    priv count_rotation: bool,
+    priv stack: ~[Frame],
+    priv placed: ~[Mask],
+    priv started: bool,
+    // A rotated twin solution waiting to be returned by the very
+    // next call to `next`.
+    priv pending: Option<Solution>
+}
+
+// A lazy enumeration of every solution for `masks` on `geo`.
+fn solutions<'a>(geo: &'a Geometry, masks: &'a [~[~[Mask]]], count_rotation: bool) -> Solutions<'a> {
+    Solutions {
+        masks: masks, geo: geo, count_rotation: count_rotation,
+        stack: ~[], placed: ~[], started: false, pending: None
+    }
+}
+
+// Drives `solutions` until it is exhausted or `stop_after` boards have
+// been yielded, folding each yielded board into a
+// `Data` the same way `handle_sol` folds one in. `solutions` already
+// yields a rotated twin as its own `Solution`, so unlike `handle_sol`
+// this never bumps `nb` by more than one per iteration.
+fn search_iter(geo: &Geometry, masks: &[~[~[Mask]]], count_rotation: bool, stop_after: int) -> Data {
+    let mut data = Data {
+        stop_after: stop_after, nb: 0, min: ~"", max: ~"",
+        count_rotation: false, shared: None
+    };
+    for sol in solutions(geo, masks, count_rotation) {
+        data.nb += 1;
+        if data.nb == 1 {
+            data.min = sol.board.clone();
+            data.max = sol.board.clone();
+        }
+        if sol.board < data.min {data.min = sol.board.clone();}
+        if sol.board > data.max {data.max = sol.board;}
+        if data.nb >= stop_after {break;}
+    }
+    data
+}
+
+impl<'self> Solutions<'self> {
+    // Pushes a frame for `board`, advancing from cell `i` to the
+    // first still-empty one and precomputing every mask that could
+    // go there.  Returns false (pushing nothing) when there is no
+    // empty cell left, i.e. `board` is itself a complete solution.
+    fn push_frame(&mut self, board: Mask, mut i: int) -> bool {
+        let n = self.geo.cells() as int;
+        while board.cells(self.geo) & (1 << i) != 0 && i < n {i += 1;}
+        if i >= n {return false;}
+
+        let mut candidates = ~[];
+        for id in range(0, self.geo.num_pieces).filter(|&id| !board.uses_piece(self.geo, id)) {
+            for &m in self.masks[id][i as uint].iter().filter(|&m| !m.overlaps(self.geo, board)) {
+                candidates.push(m);
+            }
+        }
+        self.stack.push(Frame {board: board, i: i, candidates: candidates, cursor: 0});
+        true
+    }
+}
+
+impl<'self> Iterator<Solution> for Solutions<'self> {
+    fn next(&mut self) -> Option<Solution> {
+        if self.pending.is_some() {return self.pending.take();}
+
+        if !self.started {
+            self.started = true;
+            self.push_frame(Mask(0), 0);
+        }
+
+        loop {
+            if self.stack.len() == 0 {return None;}
+            let top = self.stack.len() - 1;
+
+            if self.stack[top].cursor >= self.stack[top].candidates.len() {
+                // nothing left to try at this level: back up.
+                self.stack.pop();
+                if top > 0 {self.placed.pop();}
+                continue;
+            }
+
+            let m = self.stack[top].candidates[self.stack[top].cursor];
+            self.stack[top].cursor += 1;
+            let board = self.stack[top].board | m;
+            let i = self.stack[top].i;
+            self.placed.push(m);
+
+            if self.push_frame(board, i + 1) {
+                // still unfinished: keep descending.
+                continue;
+            }
+
+            // the board is full: a solution is found.
+            let sol1 = to_utf8(self.placed, self.geo);
+            let full_masks = self.placed_so_far();
+            self.placed.pop();
+
+            if self.count_rotation {
+                // because `make_masks` breaks the symetry, every
+                // solution corresponds to two boards: itself, and the
+                // same solution in reverse order, i.e. the board
+                // rotated by half a turn.  The rotated twin's masks
+                // aren't recomputed, so it carries no `masks`.
+                let sol2: ~str = sol1.iter().invert().collect();
+                self.pending = Some(Solution {board: sol2, masks: None});
+            }
+            return Some(Solution {board: sol1, masks: Some(full_masks)});
+        }
+    }
+}
+
+impl<'self> Solutions<'self> {
+    // A snapshot of the masks placed along the current path.
+    fn placed_so_far(&self) -> ~[Mask] {
+        self.placed.iter().map(|&m| m).collect()
+    }
+}
+
+//
+// Dancing Links (Algorithm X) backend.
+//
+// The puzzle is an exact cover problem over `geo.bits()` columns (one
+// per cell plus one per piece); `Dlx` is Knuth's toroidal quadruply-
+// linked node list, nodes kept in a single Vec and linked by index
+// instead of by pointer so it stays safe. Column 0 is the list root.
+
+struct DlxNode {
+    left: uint,
+    right: uint,
+    up: uint,
+    down: uint,
+    column: uint,
+    // the mask this row stands for; meaningless on header nodes.
+    mask: Mask,
+}
+
+struct Dlx {
+    nodes: ~[DlxNode],
+    // number of live rows in each column, indexed by column id.
+    sizes: ~[uint],
+}
+
+impl Dlx {
+    // An empty matrix with `num_columns` headers and no rows yet.
+    fn new(num_columns: uint) -> Dlx {
+        let mut nodes = ~[DlxNode {
+            left: num_columns, right: 1, up: 0, down: 0, column: 0, mask: Mask(0)
+        }];
+        for c in range(1, num_columns + 1) {
+            let right = if c == num_columns {0} else {c + 1};
+            nodes.push(DlxNode {left: c - 1, right: right, up: c, down: c, column: c, mask: Mask(0)});
+        }
+        Dlx {nodes: nodes, sizes: std::vec::from_elem(num_columns + 1, 0)}
+    }
+
+    // Appends a row with a 1 in every column of `cols`, tagged with
+    // the mask `m` it represents.
+    fn add_row(&mut self, cols: &[uint], m: Mask) {
+        let mut first = 0;
+        let mut prev = 0;
+        for (k, &c) in cols.iter().enumerate() {
+            let idx = self.nodes.len();
+            let up = self.nodes[c].up;
+            self.nodes.push(DlxNode {left: idx, right: idx, up: up, down: c, column: c, mask: m});
+            self.nodes[up].down = idx;
+            self.nodes[c].up = idx;
+            self.sizes[c] += 1;
+            if k == 0 {
+                first = idx;
+            } else {
+                self.nodes[prev].right = idx;
+                self.nodes[idx].left = prev;
+            }
+            prev = idx;
+        }
+        if cols.len() > 0 {
+            self.nodes[prev].right = first;
+            self.nodes[first].left = prev;
+        }
+    }
+
+    // Removes column `c` from the header row and, for every row
+    // passing through it, removes that row's other cells from their
+    // columns.
+    fn cover(&mut self, c: uint) {
+        let (l, r) = (self.nodes[c].left, self.nodes[c].right);
+        self.nodes[l].right = r;
+        self.nodes[r].left = l;
+        let mut i = self.nodes[c].down;
+        while i != c {
+            let mut j = self.nodes[i].right;
+            while j != i {
+                let (u, d, col) = (self.nodes[j].up, self.nodes[j].down, self.nodes[j].column);
+                self.nodes[u].down = d;
+                self.nodes[d].up = u;
+                self.sizes[col] -= 1;
+                j = self.nodes[j].right;
+            }
+            i = self.nodes[i].down;
+        }
+    }
+
+    // Exactly reverses a `cover(c)`.
+    fn uncover(&mut self, c: uint) {
+        let mut i = self.nodes[c].up;
+        while i != c {
+            let mut j = self.nodes[i].left;
+            while j != i {
+                let col = self.nodes[j].column;
+                self.sizes[col] += 1;
+                let (u, d) = (self.nodes[j].up, self.nodes[j].down);
+                self.nodes[u].down = j;
+                self.nodes[d].up = j;
+                j = self.nodes[j].left;
+            }
+            i = self.nodes[i].up;
+        }
+        let (l, r) = (self.nodes[c].left, self.nodes[c].right);
+        self.nodes[l].right = c;
+        self.nodes[r].left = c;
+    }
+
+    // The column with the fewest candidate rows (the S-heuristic), or
+    // 0 if every column has already been covered.
+    fn choose_column(&self) -> uint {
+        let mut c = self.nodes[0].right;
+        if c == 0 {return 0;}
+        let mut best = c;
+        c = self.nodes[c].right;
+        while c != 0 {
+            if self.sizes[c] < self.sizes[best] {best = c;}
+            c = self.nodes[c].right;
+        }
+        best
+    }
+}
+
+// Builds the exact-cover matrix: one row per mask `make_masks` (as
+// narrowed by `filter_masks`) produced, with 1s in the cells it covers
+// plus its piece-id column.  Keeps the existing symmetry break since
+// it is fed the already-filtered `masks` table.
+fn build_dlx(geo: &Geometry, masks: &[~[~[Mask]]]) -> Dlx {
+    let mut dlx = Dlx::new(geo.bits());
+    for (id, per_pos) in masks.iter().enumerate() {
+        for pos_masks in per_pos.iter() {
+            for &m in pos_masks.iter() {
+                let mut cols = ~[];
+                for i in range(0, geo.cells()) {
+                    if m.cells(geo) & (1 << i) != 0 {cols.push(i + 1);}
+                }
+                cols.push(geo.cells() + id + 1);
+                dlx.add_row(cols, m);
+            }
+        }
+    }
+    dlx
+}
+
+// Runs Algorithm X over `dlx`: pick the sparsest column, cover it and
+// every column touched by each of its rows in turn, recurse, then
+// uncover in reverse order.  Mirrors `search`'s own contract, consing
+// the chosen row's mask onto `cur` and calling `handle_sol` once no
+// column is left to cover.  Returns false if the search was stopped
+// before the end.
+fn search_dlx(dlx: &mut Dlx, geo: &Geometry, cur: List<Mask>, data: &mut Data) -> bool {
+    let c = dlx.choose_column();
+    if c == 0 {return handle_sol(&cur, geo, data);}
+    if dlx.sizes[c] == 0 {return true;}
+
+    dlx.cover(c);
+    let mut r = dlx.nodes[c].down;
+    while r != c {
+        let mut j = dlx.nodes[r].right;
+        while j != r {
+            dlx.cover(dlx.nodes[j].column);
+            j = dlx.nodes[j].right;
+        }
+
+        let m = dlx.nodes[r].mask;
+        let keep_going = search_dlx(dlx, geo, Cons(m, &cur), data);
+
+        let mut j = dlx.nodes[r].left;
+        while j != r {
+            dlx.uncover(dlx.nodes[j].column);
+            j = dlx.nodes[j].left;
+        }
+
+        if !keep_going {
+            dlx.uncover(c);
+            return false;
+        }
+        r = dlx.nodes[r].down;
+    }
+    dlx.uncover(c);
+    true
+}
+
+// The candidate placements for the board's very first cell: `search`
+// always starts at i = 0, so these are exactly its first-level
+// branches, and so an independent subtree for a worker thread to
+// explore on its own.
+fn top_level_branches(geo: &Geometry, masks: &[~[~[Mask]]]) -> ~[Mask] {
+    let mut res = ~[];
+    for id in range(0, geo.num_pieces) {
+        for &m in masks[id][0].iter() {
+            res.push(m);
+        }
+    }
+    res
+}
+
+// Runs `search`, fanning its first-level branches out across worker
+// threads (one per CPU, and never more than there are branches) instead of walking them on a
+// single one.  Each thread gets its own chunk of branches, its own
+// board/List prefix and its own `Data` accumulator sharing only the
+// stop-after counter with the others; partial results are merged back
+// into `data` once every thread has finished.  `masks` is read-only
+// so it is shared across threads behind an `Arc` rather than cloned.
+//
+// Note: once the shared counter reaches `stop_after`, each thread
+// stops at the next solution it finds itself, so the merged `nb` can
+// exceed `stop_after`; which branches got explored (and so which
+// `nb`, `min` and `max`) is then a function of
+// thread scheduling. That matches a sequential run exactly when
+// `stop_after` is reached right at the end of an exhaustive search
+// (true of the default, 2098), but not for an arbitrary `stop_after`.
+fn search_parallel(masks: ~[~[~[Mask]]], geo: Geometry, data: &mut Data) {
+    let branches = top_level_branches(&geo, masks);
+    if branches.len() == 0 {return;}
+    let masks = Arc::new(masks);
+    let geo = Arc::new(geo);
+    let counter = Arc::new(AtomicInt::new(0));
+    let num_workers = std::cmp::max(1, std::cmp::min(branches.len(), std::rt::util::num_cpus()));
+    let chunk_size = (branches.len() + num_workers - 1) / num_workers;
+    let chunks: ~[~[Mask]] = branches.chunks(chunk_size).map(|c| c.to_owned()).collect();
+
+    let mut partials: ~[Future<Data>] = ~[];
+    for chunk in chunks.move_iter() {
+        let masks = masks.clone();
+        let geo = geo.clone();
+        let counter = counter.clone();
+        let stop_after = data.stop_after;
+        let count_rotation = data.count_rotation;
+        partials.push(do Future::spawn {
+            let masks = masks.get();
+            let geo = geo.get();
+            let mut local = Data {
+                stop_after: stop_after, nb: 0, min: ~"", max: ~"",
+                count_rotation: count_rotation, shared: Some(counter.clone())
+            };
+            for &m in chunk.iter() {
+                if !search(masks, geo, m, 1, Cons(m, &Nil), &mut local) {break;}
+            }
+            local
+        });
+    }
+    for f in partials.move_iter() {
+        data.merge(f.unwrap());
+    }
+}
+
+// `--width`/`--height` let a caller run the board's ten pieces over a
+// differently-sized board instead of always the standard 5x10 one;
+// the piece shapes themselves stay fixed (they are hand-drawn
+// pentomino-like shapes, not generated from a count), and the
+// half-turn symmetry break only applies to the standard board, so a
+// non-default size disables it rather than assuming it holds.
+// `--iter` runs the lazy `solutions` iterator instead of `search_dlx`
+// or `search_parallel`.
 fn main () {
     let args = std::os::args();
-    let stop_after = if args.len() <= 1 {
+    let use_dlx = args.iter().any(|a| *a == ~"--dlx");
+    let use_iter = args.iter().any(|a| *a == ~"--iter");
+    let mut width = 5u;
+    let mut height = 10u;
+    let mut nums: ~[~str] = ~[];
+    let mut i = 1;
+    while i < args.len() {
+        if args[i] == ~"--dlx" || args[i] == ~"--iter" {
+            // handled above.
+        } else if args[i] == ~"--width" {
+            i += 1;
+            width = from_str(args[i]).unwrap();
+        } else if args[i] == ~"--height" {
+            i += 1;
+            height = from_str(args[i]).unwrap();
+        } else {
+            nums.push(args[i].clone());
+        }
+        i += 1;
+    }
+    let stop_after = if nums.len() == 0 {
         2098
     } else {
-        from_str(args[1]).unwrap()
+        from_str(nums[0]).unwrap()
     };
-    let masks = make_masks();
-    let masks = filter_masks(masks);
-    let mut data = Data {stop_after: stop_after, nb: 0, min: ~"", max: ~""};
-    search(masks, 0, 0, Nil, &mut data);
+    let pieces = default_pieces();
+    let geo = Geometry::new(width, height, pieces.len());
+    let symmetric_piece = if (width, height) == (5, 10) {
+        default_symmetric_piece()
+    } else {
+        None
+    };
+    let masks = make_masks(&geo, pieces, symmetric_piece);
+    let masks = filter_masks(&geo, masks);
+    let mut data = Data {
+        stop_after: stop_after, nb: 0, min: ~"", max: ~"",
+        count_rotation: symmetric_piece.is_some(), shared: None
+    };
+    if use_dlx {
+        let mut dlx = build_dlx(&geo, masks);
+        search_dlx(&mut dlx, &geo, Nil, &mut data);
+    } else if use_iter {
+        data = search_iter(&geo, masks, symmetric_piece.is_some(), stop_after);
+    } else {
+        search_parallel(masks, geo.clone(), &mut data);
+    }
     println!("{} solutions found", data.nb);
-    print_sol(data.min);
-    print_sol(data.max);
+    print_sol(data.min, &geo);
+    print_sol(data.max, &geo);
     println("");
 }